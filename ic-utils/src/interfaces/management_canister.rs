@@ -12,8 +12,9 @@ pub mod attributes;
 pub mod builders;
 #[doc(inline)]
 pub use builders::{
-    CreateCanisterBuilder, InstallBuilder, InstallChunkedCodeBuilder, InstallCodeBuilder,
-    UpdateCanisterBuilder,
+    CanisterInstallMode, CanisterUpgradeOptions, ChunkedUploader, CreateCanisterBuilder,
+    InstallBuilder, InstallChunkedCodeBuilder, InstallCodeBuilder, InstallMode,
+    UpdateCanisterBuilder, WasmMemoryPersistence,
 };
 
 /// The IC management canister.
@@ -63,6 +64,18 @@ pub enum MgmtMethod {
     StoredChunks,
     /// See [`ManagementCanister::install_chunked_code`].
     InstallChunkedCode,
+    /// See [`ManagementCanister::delete_chunks`].
+    DeleteChunks,
+    /// See [`ManagementCanister::take_canister_snapshot`].
+    TakeCanisterSnapshot,
+    /// See [`ManagementCanister::load_canister_snapshot`].
+    LoadCanisterSnapshot,
+    /// See [`ManagementCanister::list_canister_snapshots`].
+    ListCanisterSnapshots,
+    /// See [`ManagementCanister::delete_canister_snapshot`].
+    DeleteCanisterSnapshot,
+    /// See [`ManagementCanister::fetch_canister_logs`].
+    FetchCanisterLogs,
 }
 
 impl<'agent> ManagementCanister<'agent> {
@@ -100,6 +113,23 @@ pub struct StatusCallResult {
     pub cycles: Nat,
     /// The canister's reserved cycles balance.
     pub reserved_cycles: Nat,
+    /// The number of cycles the canister burns per day for idle resource consumption (storage and compute allocation).
+    pub idle_cycles_burned_per_day: Nat,
+    /// The canister's query call statistics.
+    pub query_stats: QueryStats,
+}
+
+/// The query call statistics of a canister, as returned by [`ManagementCanister::canister_status`].
+#[derive(Clone, Debug, Deserialize, CandidType)]
+pub struct QueryStats {
+    /// The total number of query calls the canister has received.
+    pub num_calls_total: Nat,
+    /// The total number of instructions the canister has executed in query calls.
+    pub num_instructions_total: Nat,
+    /// The total size, in bytes, of the payloads of query calls the canister has received.
+    pub request_payload_bytes_total: Nat,
+    /// The total size, in bytes, of the payloads of query call responses the canister has sent.
+    pub response_payload_bytes_total: Nat,
 }
 
 /// The concrete settings of a canister.
@@ -115,6 +145,22 @@ pub struct DefiniteCanisterSettings {
     pub freezing_threshold: Nat,
     /// The upper limit of the canister's reserved cycles balance.
     pub reserved_cycles_limit: Option<Nat>,
+    /// Who is allowed to read the canister's logs via [`ManagementCanister::fetch_canister_logs`].
+    pub log_visibility: LogVisibility,
+}
+
+/// Controls who may read a canister's logs via [`ManagementCanister::fetch_canister_logs`].
+#[derive(Clone, Debug, Deserialize, CandidType, PartialEq, Eq)]
+pub enum LogVisibility {
+    /// Only the canister's controllers may read its logs.
+    #[serde(rename = "controllers")]
+    Controllers,
+    /// Anyone may read the canister's logs.
+    #[serde(rename = "public")]
+    Public,
+    /// The listed principals, in addition to the controllers, may read the canister's logs.
+    #[serde(rename = "allowed_viewers")]
+    AllowedViewers(Vec<Principal>),
 }
 
 impl std::fmt::Display for StatusCallResult {
@@ -147,6 +193,60 @@ impl std::fmt::Display for CanisterStatus {
 /// A SHA-256 hash of a WASM chunk.
 pub type ChunkHash = [u8; 32];
 
+/// The ID of a canister snapshot.
+#[derive(Clone, Debug, Deserialize, CandidType, PartialEq, Eq)]
+pub struct SnapshotId(#[serde(with = "serde_bytes")] Vec<u8>);
+
+impl SnapshotId {
+    /// Construct a `SnapshotId` from raw bytes, e.g. one persisted from a previous
+    /// [`ManagementCanister::take_canister_snapshot`] call.
+    pub fn new(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+
+    /// The raw bytes of the snapshot ID.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl AsRef<[u8]> for SnapshotId {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// Metadata of a canister snapshot, as returned by [`ManagementCanister::take_canister_snapshot`]
+/// and [`ManagementCanister::list_canister_snapshots`].
+#[derive(Clone, Debug, Deserialize, CandidType)]
+pub struct Snapshot {
+    /// The ID of the snapshot.
+    pub id: SnapshotId,
+    /// The timestamp, in nanoseconds since 1970-01-01, at which the snapshot was taken.
+    pub taken_at_timestamp: u64,
+    /// The total size, in bytes, of the snapshot.
+    pub total_size: u64,
+}
+
+/// The response to [`ManagementCanister::fetch_canister_logs`].
+#[derive(Clone, Debug, Deserialize, CandidType)]
+pub struct FetchCanisterLogsResponse {
+    /// The log records the canister has written, oldest first.
+    pub canister_log_records: Vec<CanisterLogRecord>,
+}
+
+/// A single log record written by a canister, via `debug_print` or left behind by a trap.
+#[derive(Clone, Debug, Deserialize, CandidType)]
+pub struct CanisterLogRecord {
+    /// The sequence number of this record, scoped to the canister.
+    pub idx: u64,
+    /// The timestamp, in nanoseconds since 1970-01-01, at which the record was written.
+    pub timestamp_nanos: u64,
+    /// The content of the log record.
+    #[serde(with = "serde_bytes")]
+    pub content: Vec<u8>,
+}
+
 impl<'agent> ManagementCanister<'agent> {
     /// Get the status of a canister.
     pub fn canister_status(
@@ -167,6 +267,25 @@ impl<'agent> ManagementCanister<'agent> {
             .map(|result: (StatusCallResult,)| (result.0,))
     }
 
+    /// Fetch the log records a canister has written via `debug_print` or left behind by a trap.
+    pub fn fetch_canister_logs(
+        &self,
+        canister_id: &Principal,
+    ) -> impl 'agent + AsyncCall<(FetchCanisterLogsResponse,)> {
+        #[derive(CandidType)]
+        struct In {
+            canister_id: Principal,
+        }
+
+        self.query(MgmtMethod::FetchCanisterLogs.as_ref())
+            .with_arg(In {
+                canister_id: *canister_id,
+            })
+            .with_effective_canister_id(canister_id.to_owned())
+            .build()
+            .map(|result: (FetchCanisterLogsResponse,)| (result.0,))
+    }
+
     /// Create a canister.
     pub fn create_canister<'canister>(&'canister self) -> CreateCanisterBuilder<'agent, 'canister> {
         CreateCanisterBuilder::builder(self)
@@ -352,6 +471,37 @@ impl<'agent> ManagementCanister<'agent> {
             .build()
     }
 
+    /// Delete specific chunks from a canister's chunked WASM storage, without clearing the
+    /// whole store.
+    pub fn delete_chunks(
+        &self,
+        canister_id: &Principal,
+        hashes: Vec<ChunkHash>,
+    ) -> impl 'agent + AsyncCall<()> {
+        #[derive(CandidType, Deserialize)]
+        struct ChunkHashIn {
+            #[serde(with = "serde_bytes")]
+            hash: Vec<u8>,
+        }
+        #[derive(CandidType)]
+        struct Argument {
+            canister_id: Principal,
+            chunk_hashes: Vec<ChunkHashIn>,
+        }
+        self.update(MgmtMethod::DeleteChunks.as_ref())
+            .with_arg(Argument {
+                canister_id: *canister_id,
+                chunk_hashes: hashes
+                    .into_iter()
+                    .map(|hash| ChunkHashIn {
+                        hash: hash.to_vec(),
+                    })
+                    .collect(),
+            })
+            .with_effective_canister_id(*canister_id)
+            .build()
+    }
+
     /// Install a canister module previously uploaded in chunks via [`upload_chunk`](Self::upload_chunk).
     pub fn install_chunked_code<'canister>(
         &'canister self,
@@ -361,11 +511,98 @@ impl<'agent> ManagementCanister<'agent> {
         InstallChunkedCodeBuilder::builder(self, *canister_id, wasm_module_hash)
     }
 
+    /// Take a snapshot of a stopped canister's memory and state. If `replace_snapshot` is
+    /// provided, that existing snapshot is atomically replaced by the new one only once the new
+    /// snapshot has been taken successfully.
+    pub fn take_canister_snapshot(
+        &self,
+        canister_id: &Principal,
+        replace_snapshot: Option<&SnapshotId>,
+    ) -> impl 'agent + AsyncCall<(Snapshot,)> {
+        #[derive(CandidType)]
+        struct Argument<'a> {
+            canister_id: Principal,
+            replace_snapshot: Option<&'a SnapshotId>,
+        }
+
+        self.update(MgmtMethod::TakeCanisterSnapshot.as_ref())
+            .with_arg(Argument {
+                canister_id: *canister_id,
+                replace_snapshot,
+            })
+            .with_effective_canister_id(canister_id.to_owned())
+            .build()
+    }
+
+    /// Load a previously taken snapshot onto a stopped canister, replacing its memory and state.
+    pub fn load_canister_snapshot(
+        &self,
+        canister_id: &Principal,
+        snapshot_id: &SnapshotId,
+        sender_canister_version: Option<u64>,
+    ) -> impl 'agent + AsyncCall<()> {
+        #[derive(CandidType)]
+        struct Argument<'a> {
+            canister_id: Principal,
+            snapshot_id: &'a SnapshotId,
+            sender_canister_version: Option<u64>,
+        }
+
+        self.update(MgmtMethod::LoadCanisterSnapshot.as_ref())
+            .with_arg(Argument {
+                canister_id: *canister_id,
+                snapshot_id,
+                sender_canister_version,
+            })
+            .with_effective_canister_id(canister_id.to_owned())
+            .build()
+    }
+
+    /// List the snapshots currently held for a canister.
+    pub fn list_canister_snapshots(
+        &self,
+        canister_id: &Principal,
+    ) -> impl 'agent + AsyncCall<(Vec<Snapshot>,)> {
+        #[derive(CandidType)]
+        struct Argument {
+            canister_id: Principal,
+        }
+
+        self.update(MgmtMethod::ListCanisterSnapshots.as_ref())
+            .with_arg(Argument {
+                canister_id: *canister_id,
+            })
+            .with_effective_canister_id(canister_id.to_owned())
+            .build()
+            .map(|result: (Vec<Snapshot>,)| (result.0,))
+    }
+
+    /// Delete a previously taken canister snapshot.
+    pub fn delete_canister_snapshot(
+        &self,
+        canister_id: &Principal,
+        snapshot_id: &SnapshotId,
+    ) -> impl 'agent + AsyncCall<()> {
+        #[derive(CandidType)]
+        struct Argument<'a> {
+            canister_id: Principal,
+            snapshot_id: &'a SnapshotId,
+        }
+
+        self.update(MgmtMethod::DeleteCanisterSnapshot.as_ref())
+            .with_arg(Argument {
+                canister_id: *canister_id,
+                snapshot_id,
+            })
+            .with_effective_canister_id(canister_id.to_owned())
+            .build()
+    }
+
     /// Install a canister module, automatically selecting one-shot installation or chunked installation depending on module size.
     ///
-    /// # Warnings
-    ///
-    /// This will clear chunked code storage if chunked installation is used. Do not use with canisters that you are manually uploading chunked code to.
+    /// When chunked installation is used, this delegates to [`chunked_upload`](Self::chunked_upload),
+    /// so any chunks already present in the canister's chunk store (e.g. left over from a previous,
+    /// interrupted call) are reused rather than re-uploaded.
     pub fn install<'canister: 'builder, 'builder>(
         &'canister self,
         canister_id: &Principal,
@@ -373,4 +610,13 @@ impl<'agent> ManagementCanister<'agent> {
     ) -> InstallBuilder<'agent, 'canister, 'builder> {
         InstallBuilder::builder(self, canister_id, wasm)
     }
+
+    /// Upload a Wasm module to a canister's chunk store and install it, splitting it into chunks,
+    /// skipping chunks already present in the store, and uploading the rest concurrently.
+    pub fn chunked_upload<'canister>(
+        &'canister self,
+        canister_id: &Principal,
+    ) -> ChunkedUploader<'agent, 'canister> {
+        ChunkedUploader::builder(self, canister_id)
+    }
 }
@@ -0,0 +1,611 @@
+//! Builders for the various calls exposed by [`ManagementCanister`](super::ManagementCanister)
+//! that are complex enough to warrant one.
+
+use crate::{
+    call::AsyncCall,
+    interfaces::management_canister::{ChunkHash, LogVisibility, ManagementCanister, MgmtMethod},
+};
+use candid::{CandidType, Deserialize, Encode, Nat};
+use futures_util::future::join_all;
+use ic_agent::{export::Principal, AgentError};
+use std::collections::HashSet;
+
+/// The set of canister settings that can be provided when creating or updating a canister.
+#[derive(Default, Clone, Debug, CandidType, Deserialize)]
+pub struct CanisterSettings {
+    /// The set of canister controllers.
+    pub controllers: Option<Vec<Principal>>,
+    /// The allocation percentage (between 0 and 100 inclusive) for *guaranteed* compute capacity.
+    pub compute_allocation: Option<Nat>,
+    /// The allocation, in bytes, that the canister is allowed to use for storage.
+    pub memory_allocation: Option<Nat>,
+    /// The freezing threshold, in seconds.
+    pub freezing_threshold: Option<Nat>,
+    /// The upper limit of the canister's reserved cycles balance.
+    pub reserved_cycles_limit: Option<Nat>,
+    /// Who is allowed to read the canister's logs.
+    pub log_visibility: Option<LogVisibility>,
+}
+
+/// A builder for a `create_canister` call.
+pub struct CreateCanisterBuilder<'agent, 'canister: 'agent> {
+    canister: &'canister ManagementCanister<'agent>,
+    settings: CanisterSettings,
+}
+
+impl<'agent, 'canister: 'agent> CreateCanisterBuilder<'agent, 'canister> {
+    pub(super) fn builder(canister: &'canister ManagementCanister<'agent>) -> Self {
+        Self {
+            canister,
+            settings: CanisterSettings::default(),
+        }
+    }
+
+    /// Pass in an initial controller for the managed canister.
+    pub fn with_controller(mut self, controller: Principal) -> Self {
+        self.settings
+            .controllers
+            .get_or_insert_with(Vec::new)
+            .push(controller);
+        self
+    }
+
+    /// Pass in a compute allocation for the managed canister.
+    pub fn with_compute_allocation<C: Into<Nat>>(mut self, compute_allocation: C) -> Self {
+        self.settings.compute_allocation = Some(compute_allocation.into());
+        self
+    }
+
+    /// Pass in a memory allocation for the managed canister.
+    pub fn with_memory_allocation<M: Into<Nat>>(mut self, memory_allocation: M) -> Self {
+        self.settings.memory_allocation = Some(memory_allocation.into());
+        self
+    }
+
+    /// Pass in a freezing threshold for the managed canister.
+    pub fn with_freezing_threshold<F: Into<Nat>>(mut self, freezing_threshold: F) -> Self {
+        self.settings.freezing_threshold = Some(freezing_threshold.into());
+        self
+    }
+
+    /// Create the canister, returning its ID.
+    pub fn build(self) -> Result<impl 'agent + AsyncCall<(Principal,)>, AgentError> {
+        #[derive(CandidType)]
+        struct In {
+            settings: CanisterSettings,
+        }
+
+        Ok(self
+            .canister
+            .update(MgmtMethod::CreateCanister.as_ref())
+            .with_arg(In {
+                settings: self.settings,
+            })
+            .build()
+            .map(|result: (CanisterIdRecord,)| (result.0.canister_id,)))
+    }
+}
+
+#[derive(CandidType, Deserialize)]
+struct CanisterIdRecord {
+    canister_id: Principal,
+}
+
+/// A builder for an `update_settings` call.
+pub struct UpdateCanisterBuilder<'agent, 'canister: 'agent> {
+    canister: &'canister ManagementCanister<'agent>,
+    canister_id: Principal,
+    settings: CanisterSettings,
+}
+
+impl<'agent, 'canister: 'agent> UpdateCanisterBuilder<'agent, 'canister> {
+    pub(super) fn builder(
+        canister: &'canister ManagementCanister<'agent>,
+        canister_id: &Principal,
+    ) -> Self {
+        Self {
+            canister,
+            canister_id: *canister_id,
+            settings: CanisterSettings::default(),
+        }
+    }
+
+    /// Add a controller to the canister's controller list.
+    pub fn with_controller(mut self, controller: Principal) -> Self {
+        self.settings
+            .controllers
+            .get_or_insert_with(Vec::new)
+            .push(controller);
+        self
+    }
+
+    /// Pass in a compute allocation for the canister.
+    pub fn with_compute_allocation<C: Into<Nat>>(mut self, compute_allocation: C) -> Self {
+        self.settings.compute_allocation = Some(compute_allocation.into());
+        self
+    }
+
+    /// Pass in a memory allocation for the canister.
+    pub fn with_memory_allocation<M: Into<Nat>>(mut self, memory_allocation: M) -> Self {
+        self.settings.memory_allocation = Some(memory_allocation.into());
+        self
+    }
+
+    /// Pass in a freezing threshold for the canister.
+    pub fn with_freezing_threshold<F: Into<Nat>>(mut self, freezing_threshold: F) -> Self {
+        self.settings.freezing_threshold = Some(freezing_threshold.into());
+        self
+    }
+
+    /// Pass in a reserved cycles limit for the canister.
+    pub fn with_reserved_cycles_limit<R: Into<Nat>>(mut self, reserved_cycles_limit: R) -> Self {
+        self.settings.reserved_cycles_limit = Some(reserved_cycles_limit.into());
+        self
+    }
+
+    /// Pass in who is allowed to read the canister's logs.
+    pub fn with_log_visibility(mut self, log_visibility: LogVisibility) -> Self {
+        self.settings.log_visibility = Some(log_visibility);
+        self
+    }
+
+    /// Apply the settings, updating the canister.
+    pub fn build(self) -> Result<impl 'agent + AsyncCall<()>, AgentError> {
+        #[derive(CandidType)]
+        struct In {
+            canister_id: Principal,
+            settings: CanisterSettings,
+        }
+
+        Ok(self
+            .canister
+            .update(MgmtMethod::UpdateSettings.as_ref())
+            .with_arg(In {
+                canister_id: self.canister_id,
+                settings: self.settings,
+            })
+            .with_effective_canister_id(self.canister_id)
+            .build())
+    }
+}
+
+/// Controls whether the main Wasm heap is kept or replaced by an upgrade. Needed for upgrades
+/// of canisters built with enhanced orthogonal persistence (e.g. Motoko canisters), which keep
+/// their heap across upgrades instead of relying on `pre_upgrade`/`post_upgrade` serialization.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, CandidType, Deserialize)]
+pub enum WasmMemoryPersistence {
+    /// Keep the main Wasm memory as-is across the upgrade.
+    #[serde(rename = "keep")]
+    Keep,
+    /// Reinitialize the main Wasm memory, as in a classical upgrade.
+    #[serde(rename = "replace")]
+    Replace,
+}
+
+/// Additional options that refine how [`CanisterInstallMode::Upgrade`] behaves.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, CandidType, Deserialize)]
+pub struct CanisterUpgradeOptions {
+    /// If set to `true`, the canister's `pre_upgrade` hook is not executed before the new Wasm
+    /// module is installed.
+    pub skip_pre_upgrade: Option<bool>,
+    /// Whether to keep or replace the main Wasm memory across the upgrade.
+    pub wasm_memory_persistence: Option<WasmMemoryPersistence>,
+}
+
+/// The installation mode to use with [`InstallCodeBuilder`].
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Deserialize)]
+pub enum CanisterInstallMode {
+    /// Install code into an empty canister.
+    #[serde(rename = "install")]
+    Install,
+    /// Install code into a canister that already has code, discarding its state.
+    #[serde(rename = "reinstall")]
+    Reinstall,
+    /// Install code into a canister that already has code, retaining its state, with the given
+    /// options refining how the upgrade behaves.
+    #[serde(rename = "upgrade")]
+    Upgrade(Option<CanisterUpgradeOptions>),
+}
+
+/// A builder for an `install_code` call.
+pub struct InstallCodeBuilder<'agent, 'canister: 'agent> {
+    canister: &'canister ManagementCanister<'agent>,
+    canister_id: Principal,
+    wasm: &'canister [u8],
+    arg: Vec<u8>,
+    mode: CanisterInstallMode,
+}
+
+impl<'agent, 'canister: 'agent> InstallCodeBuilder<'agent, 'canister> {
+    pub(super) fn builder(
+        canister: &'canister ManagementCanister<'agent>,
+        canister_id: &Principal,
+        wasm: &'canister [u8],
+    ) -> Self {
+        Self {
+            canister,
+            canister_id: *canister_id,
+            wasm,
+            arg: Encode!().unwrap(),
+            mode: CanisterInstallMode::Install,
+        }
+    }
+
+    /// Set the installation mode. Defaults to [`CanisterInstallMode::Install`].
+    pub fn with_mode(self, mode: CanisterInstallMode) -> Self {
+        Self { mode, ..self }
+    }
+
+    /// Set whether the canister's `pre_upgrade` hook should be skipped. Sets the mode to
+    /// [`CanisterInstallMode::Upgrade`] if it is not already, keeping any upgrade options already
+    /// set; a later [`with_mode`](Self::with_mode) call replaces the mode and these options.
+    pub fn with_skip_pre_upgrade(mut self, skip_pre_upgrade: bool) -> Self {
+        self.upgrade_options_mut().skip_pre_upgrade = Some(skip_pre_upgrade);
+        self
+    }
+
+    /// Set whether the canister's main Wasm memory should be kept or replaced across the
+    /// upgrade. Sets the mode to [`CanisterInstallMode::Upgrade`] if it is not already, keeping
+    /// any upgrade options already set; a later [`with_mode`](Self::with_mode) call replaces the
+    /// mode and these options.
+    pub fn with_wasm_memory_persistence(mut self, persistence: WasmMemoryPersistence) -> Self {
+        self.upgrade_options_mut().wasm_memory_persistence = Some(persistence);
+        self
+    }
+
+    fn upgrade_options_mut(&mut self) -> &mut CanisterUpgradeOptions {
+        if !matches!(self.mode, CanisterInstallMode::Upgrade(_)) {
+            self.mode = CanisterInstallMode::Upgrade(None);
+        }
+        match &mut self.mode {
+            CanisterInstallMode::Upgrade(options) => options.get_or_insert_with(Default::default),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Set the argument to the canister's `canister_init`/`post_upgrade` hook, already Candid-encoded.
+    pub fn with_raw_arg(self, arg: Vec<u8>) -> Self {
+        Self { arg, ..self }
+    }
+
+    /// Set the argument to the canister's `canister_init`/`post_upgrade` hook, Candid-encoding it.
+    pub fn with_arg<A: CandidType>(self, arg: A) -> Self {
+        self.with_raw_arg(Encode!(&arg).unwrap())
+    }
+
+    /// Install the code.
+    pub fn build(self) -> Result<impl 'agent + AsyncCall<()>, AgentError> {
+        #[derive(CandidType, Deserialize)]
+        struct In<'a> {
+            mode: CanisterInstallMode,
+            canister_id: Principal,
+            #[serde(with = "serde_bytes")]
+            wasm_module: &'a [u8],
+            #[serde(with = "serde_bytes")]
+            arg: Vec<u8>,
+        }
+
+        Ok(self
+            .canister
+            .update(MgmtMethod::InstallCode.as_ref())
+            .with_arg(In {
+                mode: self.mode,
+                canister_id: self.canister_id,
+                wasm_module: self.wasm,
+                arg: self.arg,
+            })
+            .with_effective_canister_id(self.canister_id)
+            .build())
+    }
+}
+
+/// The size, in bytes, above which [`InstallBuilder`] uploads the Wasm module in chunks instead
+/// of passing it inline to `install_code`.
+const CHUNKED_INSTALL_THRESHOLD: usize = 1_000_000;
+
+/// The installation mode to use with [`InstallBuilder`]. Unlike [`CanisterInstallMode`], this
+/// also supports [`Auto`](Self::Auto), which picks install or upgrade on the caller's behalf.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InstallMode {
+    /// Install code into an empty canister.
+    Install,
+    /// Install code into a canister that already has code, discarding its state.
+    Reinstall,
+    /// Upgrade a canister that already has code, applying the given options.
+    Upgrade(CanisterUpgradeOptions),
+    /// Install if the canister is empty, or upgrade (applying the given options as hints) if it
+    /// already has code installed.
+    Auto(CanisterUpgradeOptions),
+}
+
+impl Default for InstallMode {
+    fn default() -> Self {
+        Self::Auto(CanisterUpgradeOptions::default())
+    }
+}
+
+/// A builder for the higher-level [`install`](ManagementCanister::install) call, which picks
+/// one-shot or chunked installation depending on module size.
+pub struct InstallBuilder<'agent, 'canister: 'agent, 'builder> {
+    canister: &'canister ManagementCanister<'agent>,
+    canister_id: Principal,
+    wasm: &'builder [u8],
+    arg: Vec<u8>,
+    mode: InstallMode,
+}
+
+impl<'agent, 'canister: 'agent, 'builder> InstallBuilder<'agent, 'canister, 'builder> {
+    pub(super) fn builder(
+        canister: &'canister ManagementCanister<'agent>,
+        canister_id: &Principal,
+        wasm: &'builder [u8],
+    ) -> Self {
+        Self {
+            canister,
+            canister_id: *canister_id,
+            wasm,
+            arg: Encode!().unwrap(),
+            mode: InstallMode::default(),
+        }
+    }
+
+    /// Set the installation mode. Defaults to [`InstallMode::Auto`].
+    pub fn with_mode(self, mode: InstallMode) -> Self {
+        Self { mode, ..self }
+    }
+
+    /// Set the argument to the canister's `canister_init`/`post_upgrade` hook, already Candid-encoded.
+    pub fn with_raw_arg(self, arg: Vec<u8>) -> Self {
+        Self { arg, ..self }
+    }
+
+    /// Set the argument to the canister's `canister_init`/`post_upgrade` hook, Candid-encoding it.
+    pub fn with_arg<A: CandidType>(self, arg: A) -> Self {
+        self.with_raw_arg(Encode!(&arg).unwrap())
+    }
+
+    /// Install the canister module, choosing between one-shot and chunked installation, and
+    /// between installing and upgrading if the mode is [`InstallMode::Auto`].
+    pub async fn build(self) -> Result<(), AgentError> {
+        let mode = match self.mode {
+            InstallMode::Auto(options) => {
+                let (status,) = self
+                    .canister
+                    .canister_status(&self.canister_id)
+                    .call_and_wait()
+                    .await?;
+                if status.module_hash.is_some() {
+                    CanisterInstallMode::Upgrade(Some(options))
+                } else {
+                    CanisterInstallMode::Install
+                }
+            }
+            InstallMode::Install => CanisterInstallMode::Install,
+            InstallMode::Reinstall => CanisterInstallMode::Reinstall,
+            InstallMode::Upgrade(options) => CanisterInstallMode::Upgrade(Some(options)),
+        };
+
+        if self.wasm.len() <= CHUNKED_INSTALL_THRESHOLD {
+            self.canister
+                .install_code(&self.canister_id, self.wasm)
+                .with_mode(mode)
+                .with_raw_arg(self.arg)
+                .build()?
+                .call_and_wait()
+                .await
+        } else {
+            use sha2::Digest;
+            let wasm_module_hash: ChunkHash = sha2::Sha256::digest(self.wasm).into();
+            self.canister
+                .chunked_upload(&self.canister_id)
+                .upload_and_install(self.wasm, wasm_module_hash, mode, self.arg)
+                .await
+        }
+    }
+}
+
+/// A builder for an `install_chunked_code` call.
+pub struct InstallChunkedCodeBuilder<'agent, 'canister: 'agent> {
+    canister: &'canister ManagementCanister<'agent>,
+    canister_id: Principal,
+    wasm_module_hash: ChunkHash,
+    store_canister_id: Option<Principal>,
+    chunk_hashes_list: Vec<ChunkHash>,
+    arg: Vec<u8>,
+    mode: CanisterInstallMode,
+}
+
+impl<'agent, 'canister: 'agent> InstallChunkedCodeBuilder<'agent, 'canister> {
+    pub(super) fn builder(
+        canister: &'canister ManagementCanister<'agent>,
+        canister_id: Principal,
+        wasm_module_hash: ChunkHash,
+    ) -> Self {
+        Self {
+            canister,
+            canister_id,
+            wasm_module_hash,
+            store_canister_id: None,
+            chunk_hashes_list: vec![],
+            arg: Encode!().unwrap(),
+            mode: CanisterInstallMode::Install,
+        }
+    }
+
+    /// Set the installation mode. Defaults to [`CanisterInstallMode::Install`].
+    pub fn with_mode(self, mode: CanisterInstallMode) -> Self {
+        Self { mode, ..self }
+    }
+
+    /// Set the canister whose chunk store the chunks were uploaded to, if not `canister_id` itself.
+    pub fn with_store_canister_id(self, store_canister_id: Principal) -> Self {
+        Self {
+            store_canister_id: Some(store_canister_id),
+            ..self
+        }
+    }
+
+    /// Set the ordered list of chunk hashes that assemble into the target Wasm module.
+    pub fn with_chunk_hashes_list(self, chunk_hashes_list: Vec<ChunkHash>) -> Self {
+        Self {
+            chunk_hashes_list,
+            ..self
+        }
+    }
+
+    /// Set the argument to the canister's `canister_init`/`post_upgrade` hook, already Candid-encoded.
+    pub fn with_raw_arg(self, arg: Vec<u8>) -> Self {
+        Self { arg, ..self }
+    }
+
+    /// Set the argument to the canister's `canister_init`/`post_upgrade` hook, Candid-encoding it.
+    pub fn with_arg<A: CandidType>(self, arg: A) -> Self {
+        self.with_raw_arg(Encode!(&arg).unwrap())
+    }
+
+    /// Install the previously uploaded chunks.
+    pub fn build(self) -> Result<impl 'agent + AsyncCall<()>, AgentError> {
+        #[derive(CandidType, Deserialize)]
+        struct In<'a> {
+            mode: CanisterInstallMode,
+            target_canister: Principal,
+            store_canister: Option<Principal>,
+            chunk_hashes_list: Vec<ChunkHashIn>,
+            #[serde(with = "serde_bytes")]
+            wasm_module_hash: &'a [u8],
+            #[serde(with = "serde_bytes")]
+            arg: Vec<u8>,
+        }
+        #[derive(CandidType, Deserialize)]
+        struct ChunkHashIn {
+            #[serde(with = "serde_bytes")]
+            hash: Vec<u8>,
+        }
+
+        Ok(self
+            .canister
+            .update(MgmtMethod::InstallChunkedCode.as_ref())
+            .with_arg(In {
+                mode: self.mode,
+                target_canister: self.canister_id,
+                store_canister: self.store_canister_id,
+                chunk_hashes_list: self
+                    .chunk_hashes_list
+                    .into_iter()
+                    .map(|hash| ChunkHashIn {
+                        hash: hash.to_vec(),
+                    })
+                    .collect(),
+                wasm_module_hash: &self.wasm_module_hash,
+                arg: self.arg,
+            })
+            .with_effective_canister_id(self.canister_id)
+            .build())
+    }
+}
+
+/// The maximum size, in bytes, of a single chunk accepted by the chunk store.
+const MAX_CHUNK_SIZE: usize = 1_048_576;
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Orchestrates uploading a Wasm module to a canister's chunk store and installing it, so
+/// callers don't have to manually slice the module, track chunk hashes, or worry about paying
+/// for an `install_chunked_code` call that fails because of a hash mismatch.
+pub struct ChunkedUploader<'agent, 'canister: 'agent> {
+    canister: &'canister ManagementCanister<'agent>,
+    canister_id: Principal,
+    max_concurrent_uploads: usize,
+}
+
+impl<'agent, 'canister: 'agent> ChunkedUploader<'agent, 'canister> {
+    pub(super) fn builder(
+        canister: &'canister ManagementCanister<'agent>,
+        canister_id: &Principal,
+    ) -> Self {
+        Self {
+            canister,
+            canister_id: *canister_id,
+            max_concurrent_uploads: 8,
+        }
+    }
+
+    /// Set the maximum number of chunks to upload concurrently. Defaults to 8. Clamped to at
+    /// least 1, since a limit of 0 would make the upload batching in
+    /// [`upload_and_install`](Self::upload_and_install) panic.
+    pub fn with_max_concurrent_uploads(self, max_concurrent_uploads: usize) -> Self {
+        Self {
+            max_concurrent_uploads: max_concurrent_uploads.max(1),
+            ..self
+        }
+    }
+
+    /// Split `wasm` into `<=1 MiB` chunks, upload whichever of them aren't already present in
+    /// the canister's chunk store, and install the assembled module.
+    ///
+    /// `expected_module_hash` is checked against the hash of `wasm` computed locally before any
+    /// call is made to the replica, so a mismatch is caught for free instead of costing cycles on
+    /// an `install_chunked_code` call that would be rejected anyway. Because already-stored
+    /// chunks are skipped, retrying this call after an interrupted upload only re-uploads
+    /// whatever didn't make it the first time.
+    pub async fn upload_and_install(
+        &self,
+        wasm: &[u8],
+        expected_module_hash: ChunkHash,
+        mode: CanisterInstallMode,
+        arg: Vec<u8>,
+    ) -> Result<(), AgentError> {
+        use sha2::Digest;
+
+        let module_hash: ChunkHash = sha2::Sha256::digest(wasm).into();
+        if module_hash != expected_module_hash {
+            return Err(AgentError::MessageError(format!(
+                "computed module hash {} does not match expected hash {}",
+                to_hex(&module_hash),
+                to_hex(&expected_module_hash),
+            )));
+        }
+
+        let chunks: Vec<&[u8]> = wasm.chunks(MAX_CHUNK_SIZE).collect();
+        let chunk_hashes_list: Vec<ChunkHash> = chunks
+            .iter()
+            .map(|chunk| sha2::Sha256::digest(chunk).into())
+            .collect();
+
+        let (already_stored,) = self
+            .canister
+            .stored_chunks(&self.canister_id)
+            .call_and_wait()
+            .await?;
+        let already_stored: HashSet<ChunkHash> = already_stored.into_iter().collect();
+
+        let to_upload: Vec<&[u8]> = chunks
+            .iter()
+            .zip(chunk_hashes_list.iter())
+            .filter(|(_, hash)| !already_stored.contains(*hash))
+            .map(|(chunk, _)| *chunk)
+            .collect();
+
+        for batch in to_upload.chunks(self.max_concurrent_uploads) {
+            let uploads = batch.iter().map(|chunk| {
+                self.canister
+                    .upload_chunk(&self.canister_id, chunk)
+                    .call_and_wait()
+            });
+            for result in join_all(uploads).await {
+                result?;
+            }
+        }
+
+        self.canister
+            .install_chunked_code(&self.canister_id, module_hash)
+            .with_mode(mode)
+            .with_chunk_hashes_list(chunk_hashes_list)
+            .with_raw_arg(arg)
+            .build()?
+            .call_and_wait()
+            .await
+    }
+}